@@ -1,242 +1,804 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
 
 #[derive(Debug, Clone, PartialEq)]
 enum Token {
-    Number(i64),
-    Plus,
-    Mul,
+    Number(Value),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+    /// A unary `-`, distinguished from binary subtraction (`Op('-')`) at
+    /// tokenize time based on whether an operand was expected.
+    Neg,
+}
+
+/// A numeric literal's value. Arithmetic stays in `Int` as long as every
+/// operand is an integer (preserving the exact, overflow-checked behavior
+/// the rest of the crate relies on); an operation with a `Float` operand is
+/// computed in `f64` and yields a `Float`, while integer-only sub-expressions
+/// elsewhere in the same expression stay `Int` (so `1.5 + 7 / 2` is `4.5`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Int(i64),
+    Float(f64),
+}
+
+impl Value {
+    fn as_f64(self) -> f64 {
+        match self {
+            Value::Int(n) => n as f64,
+            Value::Float(f) => f,
+        }
+    }
+
+    fn as_i64(self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(n),
+            Value::Float(f) if f.fract() == 0.0 => Some(f as i64),
+            Value::Float(_) => None,
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// Describes one binary operator: its symbol, how to apply it, and how it
+/// binds relative to other operators.
+///
+/// `apply` returns `None` rather than panicking on inputs it can't handle
+/// (e.g. division by zero, or a negative integer exponent), so that
+/// evaluation can fail cleanly instead of crashing.
+struct Operator {
+    symbol: char,
+    apply: fn(Value, Value) -> Option<Value>,
+    precedence: u8,
+    is_left_associative: bool,
+}
+
+/// The table of supported operators, consulted by both the tokenizer (to
+/// recognize operator characters) and the parser (to decide precedence and
+/// associativity during parsing).
+const OPERATORS: &[Operator] = &[
+    Operator {
+        symbol: '+',
+        apply: |a, b| match (a, b) {
+            (Value::Int(x), Value::Int(y)) => x.checked_add(y).map(Value::Int),
+            _ => Some(Value::Float(a.as_f64() + b.as_f64())),
+        },
+        precedence: 1,
+        is_left_associative: true,
+    },
+    Operator {
+        symbol: '-',
+        apply: |a, b| match (a, b) {
+            (Value::Int(x), Value::Int(y)) => x.checked_sub(y).map(Value::Int),
+            _ => Some(Value::Float(a.as_f64() - b.as_f64())),
+        },
+        precedence: 1,
+        is_left_associative: true,
+    },
+    Operator {
+        symbol: '*',
+        apply: |a, b| match (a, b) {
+            (Value::Int(x), Value::Int(y)) => x.checked_mul(y).map(Value::Int),
+            _ => Some(Value::Float(a.as_f64() * b.as_f64())),
+        },
+        precedence: 2,
+        is_left_associative: true,
+    },
+    Operator {
+        symbol: '/',
+        apply: |a, b| match (a, b) {
+            (Value::Int(x), Value::Int(y)) => x.checked_div(y).map(Value::Int),
+            _ => {
+                let result = a.as_f64() / b.as_f64();
+                result.is_finite().then_some(Value::Float(result))
+            }
+        },
+        precedence: 2,
+        is_left_associative: true,
+    },
+    Operator {
+        symbol: '^',
+        apply: |a, b| match (a, b) {
+            (Value::Int(x), Value::Int(y)) => {
+                let exponent = u32::try_from(y).ok()?;
+                x.checked_pow(exponent).map(Value::Int)
+            }
+            _ => {
+                let result = a.as_f64().powf(b.as_f64());
+                result.is_finite().then_some(Value::Float(result))
+            }
+        },
+        precedence: 3,
+        is_left_associative: false,
+    },
+];
+
+/// Look up an operator descriptor by its symbol.
+fn operator(symbol: char) -> Option<&'static Operator> {
+    OPERATORS.iter().find(|op| op.symbol == symbol)
+}
+
+/// The precedence unary minus binds at: looser than `^` (so `-2 ^ 2` parses
+/// as `-(2 ^ 2)`, the conventional reading) but tighter than every other
+/// binary operator (so `-2 * 3` parses as `(-2) * 3`). Derived from the
+/// `^` entry in `OPERATORS` rather than hard-coded, so it stays correct if
+/// the table changes.
+fn unary_minus_precedence() -> u8 {
+    operator('^').map(|op| op.precedence).unwrap_or(u8::MAX)
+}
+
+/// An arithmetic expression tree.
+///
+/// `Paren` records that a sub-expression was explicitly wrapped in
+/// parentheses in the source text, so that it can be rendered back out
+/// with its parentheses intact.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(Value),
+    Var(String),
+    Func(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    BinOp(char, Box<Expr>, Box<Expr>),
+    Paren(Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluate the expression tree to a single value, looking up variables
+    /// and functions in `ctx`. Returns `None` if an operator failed
+    /// (division by zero, overflow, negative integer exponent) or if a
+    /// variable or function name isn't bound in `ctx`.
+    fn eval(&self, ctx: &Context) -> Option<Value> {
+        match self {
+            Expr::Num(v) => Some(*v),
+            Expr::Var(name) => ctx.var(name),
+            Expr::Func(name, args) => {
+                let values: Option<Vec<Value>> = args.iter().map(|arg| arg.eval(ctx)).collect();
+                ctx.call(name, &values?)
+            }
+            Expr::Neg(inner) => match inner.eval(ctx)? {
+                Value::Int(n) => n.checked_neg().map(Value::Int),
+                Value::Float(f) => Some(Value::Float(-f)),
+            },
+            Expr::BinOp(symbol, lhs, rhs) => {
+                let l = lhs.eval(ctx)?;
+                let r = rhs.eval(ctx)?;
+                let op = operator(*symbol)?;
+                (op.apply)(l, r)
+            }
+            Expr::Paren(inner) => inner.eval(ctx),
+        }
+    }
+
+    /// Render the expression back to a string, preserving explicit
+    /// parentheses that were present in the source.
+    fn render(&self) -> String {
+        match self {
+            Expr::Num(v) => v.to_string(),
+            Expr::Var(name) => name.clone(),
+            Expr::Func(name, args) => {
+                let rendered_args: Vec<String> = args.iter().map(Expr::render).collect();
+                format!("{}({})", name, rendered_args.join(", "))
+            }
+            Expr::Neg(inner) => format!("-{}", inner.render()),
+            Expr::BinOp(symbol, lhs, rhs) => {
+                format!("{} {} {}", lhs.render(), symbol, rhs.render())
+            }
+            Expr::Paren(inner) => format!("({})", inner.render()),
+        }
+    }
+}
+
+/// A built-in function's implementation: it takes the already-evaluated
+/// argument values and returns the result, or `None` on a domain error
+/// (wrong arity, wrong type).
+type BuiltinFn = fn(&[Value]) -> Option<Value>;
+
+/// An evaluation context: the variable bindings and callable functions that
+/// `Expr::Var` and `Expr::Func` nodes are resolved against.
+struct Context {
+    vars: HashMap<String, Value>,
+    funcs: HashMap<String, BuiltinFn>,
+}
+
+impl Context {
+    /// A context pre-populated with the built-in functions (`abs`, `min`,
+    /// `max`, `gcd`) and no variable bindings.
+    fn new() -> Self {
+        let mut funcs: HashMap<String, BuiltinFn> = HashMap::new();
+        funcs.insert("abs".to_string(), |args| match args {
+            [Value::Int(n)] => Some(Value::Int(n.abs())),
+            [Value::Float(f)] => Some(Value::Float(f.abs())),
+            _ => None,
+        });
+        funcs.insert("min".to_string(), |args| {
+            args.iter()
+                .copied()
+                .min_by(|a, b| a.as_f64().partial_cmp(&b.as_f64()).unwrap())
+        });
+        funcs.insert("max".to_string(), |args| {
+            args.iter()
+                .copied()
+                .max_by(|a, b| a.as_f64().partial_cmp(&b.as_f64()).unwrap())
+        });
+        funcs.insert("gcd".to_string(), |args| match args {
+            [a, b] => Some(Value::Int(gcd(a.as_i64()?, b.as_i64()?))),
+            _ => None,
+        });
+        Context {
+            vars: HashMap::new(),
+            funcs,
+        }
+    }
+
+    /// Bind a variable name to a value, overwriting any previous binding.
+    fn set_var(&mut self, name: &str, value: impl Into<Value>) {
+        self.vars.insert(name.to_string(), value.into());
+    }
+
+    fn var(&self, name: &str) -> Option<Value> {
+        self.vars.get(name).copied()
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Option<Value> {
+        (self.funcs.get(name)?)(args)
+    }
+}
+
+/// Greatest common divisor, via the Euclidean algorithm.
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
 }
 
 fn main() {
     // The given expression:
     let expression = "1 + 2 * 3 + 4 * 5 + 6 * 7 + 8 * 9";
     let tokens = tokenize(expression);
+    let (operands, operators) =
+        split_into_operands_and_operators(&tokens).expect("expression must be a flat sequence of numbers and operators");
 
-    let target_value = 479;
-    let mut results = Vec::new();
-
-    // Try placing parentheses around every possible sub-expression
-    // We'll consider every pair of indices that correspond to a valid sub-expression.
-    for start in 0..tokens.len() {
-        for end in start + 1..tokens.len() {
-            // We only consider substrings that contain at least one operator
-            // (because parenthesizing just a single number or no operators doesn't make sense)
-            if is_valid_subexpression(&tokens, start, end) {
-                if let Some(value) = evaluate_with_parentheses(&tokens, start, end) {
-                    if value == target_value {
-                        // Record the actual string representation of this particular parenthesization
-                        let parenthesized = insert_parentheses(&tokens, start, end);
-                        results.push(parenthesized);
-                    }
-                }
-            }
-        }
-    }
+    let target_value = Value::Int(479);
+    let mut memo = HashMap::new();
+    let solutions = solve(&operands, &operators, &mut memo, 0, operands.len() - 1);
 
-    // Remove duplicates
+    // Keep only the distinct renderings that land on the target value.
+    let mut results: Vec<String> = solutions
+        .into_iter()
+        .filter(|(value, _)| *value == target_value)
+        .map(|(_, rendered)| rendered)
+        .collect();
     results.sort();
     results.dedup();
 
     if results.is_empty() {
         println!(
-            "No single-pair parenthetical placement found that results in {}.",
+            "No full parenthesization was found that results in {}.",
             target_value
         );
     } else {
         println!("Found the following ways to achieve {}:", target_value);
+        let ctx = Context::new();
         for r in results {
-            println!("{}", r);
+            // Re-parse each candidate into an `Expr` tree so it can be
+            // displayed through `Expr::render` rather than the raw string
+            // `solve` produced, and cross-check the AST path and the RPN
+            // path against each other, showing the RPN form as a compact
+            // canonical representation.
+            let candidate_tokens = tokenize(&r);
+            let expr = parse(&candidate_tokens).expect("solve only produces well-formed expressions");
+            let ast_value = expr.eval(&ctx);
+            let rpn = to_rpn(&candidate_tokens);
+            let rpn_value = rpn.as_ref().and_then(|rpn| eval_rpn(rpn));
+            // A real assertion, not `debug_assert_eq!`: this check is the
+            // whole point of having two independent evaluation paths, so it
+            // must still catch a regression in a release build.
+            assert_eq!(
+                ast_value,
+                Some(target_value),
+                "AST evaluation of {:?} disagreed with the search that produced it",
+                expr.render()
+            );
+            assert_eq!(
+                rpn_value,
+                ast_value,
+                "RPN evaluation of {:?} disagreed with the AST path",
+                expr.render()
+            );
+
+            let postfix = rpn.map(|rpn| render_rpn(&rpn)).unwrap_or_default();
+            println!("{}  [rpn: {}]", expr.render(), postfix);
         }
     }
-}
 
-/// Tokenize the input expression into numbers and operators.
-fn tokenize(expr: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    for part in expr.split_whitespace() {
-        if let Ok(num) = i64::from_str(part) {
-            tokens.push(Token::Number(num));
-        } else {
-            match part {
-                "+" => tokens.push(Token::Plus),
-                "*" => tokens.push(Token::Mul),
-                _ => {}
-            }
+    // Beyond the fixed numeric search above, the same parse/eval pipeline
+    // resolves named variables and the built-in functions against a
+    // `Context` binding, e.g. solving `a * x + b` for a given `a`, `b`, `x`.
+    let mut var_ctx = Context::new();
+    var_ctx.set_var("a", 2);
+    var_ctx.set_var("b", 3);
+    var_ctx.set_var("x", 5);
+    println!("\nWith a = 2, b = 3, x = 5:");
+    for var_expr in ["a * x + b", "max(a, x) + gcd(a, b)"] {
+        match parse(&tokenize(var_expr)).and_then(|expr| {
+            let value = expr.eval(&var_ctx)?;
+            Some((expr, value))
+        }) {
+            Some((expr, value)) => println!("{} = {}", expr.render(), value),
+            None => println!("{} failed to evaluate", var_expr),
         }
     }
-    tokens
 }
 
-/// Check if the subexpression from `start` to `end` (inclusive) contains at least one operator.
-fn is_valid_subexpression(tokens: &[Token], start: usize, end: usize) -> bool {
-    let mut has_operator = false;
-    let mut has_number = false;
-    for i in start..=end {
-        match tokens[i] {
-            Token::Number(_) => has_number = true,
-            Token::Plus | Token::Mul => has_operator = true,
+/// Split a flat token stream (no parentheses) into its alternating operands
+/// and operators: `n0 op0 n1 op1 ... nk`.
+fn split_into_operands_and_operators(tokens: &[Token]) -> Option<(Vec<Value>, Vec<char>)> {
+    let mut operands = Vec::new();
+    let mut operators = Vec::new();
+
+    let mut expect_operand = true;
+    for token in tokens {
+        match (token, expect_operand) {
+            (Token::Number(n), true) => {
+                operands.push(*n);
+                expect_operand = false;
+            }
+            (Token::Op(symbol), false) => {
+                operators.push(*symbol);
+                expect_operand = true;
+            }
+            _ => return None,
         }
     }
-    // Must contain at least one operator and at least one number
-    has_operator && has_number
+
+    if expect_operand || operands.is_empty() {
+        return None;
+    }
+    Some((operands, operators))
 }
 
-/// Evaluate the entire expression normally (with * having precedence over +).
-fn evaluate_expression(tokens: &[Token]) -> Option<i64> {
-    if tokens.is_empty() {
-        return None; // No expression at all
+/// All distinct `(value, rendered string)` pairs obtainable by fully
+/// parenthesizing the operands `operands[i..=j]` with the operators between
+/// them (`operators[i..j]`), trying every possible outermost split point.
+///
+/// This is the classic "different ways to add parentheses" recursion: for
+/// each operator position `p` in `i..j`, everything to its left is itself
+/// fully parenthesized (`solve(i, p)`), everything to its right too
+/// (`solve(p + 1, j)`), and every combination of a left and right value is
+/// combined through that operator. Results are memoized on `(i, j)` since
+/// the number of trees is the Catalan number of `j - i`, and the same
+/// sub-range is revisited by many different outer splits.
+fn solve(
+    operands: &[Value],
+    operators: &[char],
+    memo: &mut HashMap<(usize, usize), Vec<(Value, String)>>,
+    i: usize,
+    j: usize,
+) -> Vec<(Value, String)> {
+    if i == j {
+        return vec![(operands[i], operands[i].to_string())];
+    }
+    if let Some(cached) = memo.get(&(i, j)) {
+        return cached.clone();
     }
 
-    // We'll parse the expression into terms separated by pluses.
-    // Each term is a product of one or more numbers.
-    let mut terms: Vec<i64> = Vec::new();
-    let mut current_product: Option<i64> = None;
+    let mut combined = Vec::new();
+    for p in i..j {
+        let symbol = operators[p];
+        let Some(op) = operator(symbol) else {
+            continue;
+        };
 
-    enum Expectation {
-        Number,
-        Operator,
+        let left = solve(operands, operators, memo, i, p);
+        let right = solve(operands, operators, memo, p + 1, j);
+        for (left_value, left_str) in &left {
+            for (right_value, right_str) in &right {
+                if let Some(value) = (op.apply)(*left_value, *right_value) {
+                    let rendered = format!("({} {} {})", left_str, symbol, right_str);
+                    combined.push((value, rendered));
+                }
+            }
+        }
     }
 
-    // At the start of the expression, we expect a number.
-    let mut expectation = Expectation::Number;
+    combined.sort_by(|a, b| a.0.as_f64().partial_cmp(&b.0.as_f64()).unwrap().then_with(|| a.1.cmp(&b.1)));
+    combined.dedup();
 
+    memo.insert((i, j), combined.clone());
+    combined
+}
+
+/// Tokenize the input expression into numbers, identifiers, operators,
+/// parentheses and commas (the last needed to separate function arguments).
+///
+/// This is a character-level lexer: it first normalizes the input by
+/// stripping all whitespace, so callers can write `"1+2*3"` just as well as
+/// `"1 + 2 * 3"`. Digit runs are scanned directly (allowing multi-digit and,
+/// with a decimal point, floating-point literals), and a `-` is emitted as
+/// `Token::Neg` rather than `Token::Op('-')` whenever it appears where an
+/// operand is expected (at the start of the expression, or right after an
+/// operator, `(` or `,`) so the parser can tell unary negation apart from
+/// binary subtraction.
+fn tokenize(expr: &str) -> Vec<Token> {
+    let chars: Vec<char> = expr.chars().filter(|c| !c.is_whitespace()).collect();
+    let mut tokens = Vec::new();
+    let mut expect_operand = true;
     let mut i = 0;
-    while i < tokens.len() {
-        match (&tokens[i], &expectation) {
-            // Expecting a number and we got one
-            (Token::Number(n), Expectation::Number) => {
-                // If we currently have no product in progress, start one.
-                // If we do have one, that would mean we got two numbers in a row without an operator,
-                // which should be invalid in a well-formed expression.
-                if current_product.is_some() {
-                    return None;
-                }
-                current_product = Some(*n);
-                expectation = Expectation::Operator;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
                 i += 1;
             }
-
-            // Expecting an operator and got a plus
-            (Token::Plus, Expectation::Operator) => {
-                // Plus means we commit the current product to terms and reset for the next term.
-                if let Some(prod) = current_product.take() {
-                    terms.push(prod);
-                } else {
-                    // We got a plus but no current product, invalid
-                    return None;
+            let is_float = chars.get(i) == Some(&'.')
+                && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+            if is_float {
+                i += 1; // consume '.'
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
                 }
-                expectation = Expectation::Number;
-                i += 1;
             }
 
-            // Expecting an operator and got a multiplication
-            (Token::Mul, Expectation::Operator) => {
-                // Multiplication means we should multiply the current product with the next number.
-                // But we must check that the next token is a number.
-                if current_product.is_none() {
-                    return None;
-                }
-                if i + 1 >= tokens.len() {
-                    return None; // Mul at the end with no following number
-                }
-                if let Token::Number(m) = tokens[i + 1] {
-                    // multiply current_product by m
-                    let prod = current_product.unwrap();
-                    current_product = Some(prod * m);
-                    i += 2; // move past Mul and the Number
-                    expectation = Expectation::Operator;
-                } else {
-                    return None; // Mul not followed by a number
+            let literal: String = chars[start..i].iter().collect();
+            if is_float {
+                if let Ok(f) = f64::from_str(&literal) {
+                    tokens.push(Token::Number(Value::Float(f)));
                 }
+            } else if let Ok(n) = i64::from_str(&literal) {
+                tokens.push(Token::Number(Value::Int(n)));
             }
+            expect_operand = false;
+            continue;
+        }
 
-            // If we are expecting a number but got an operator, that's invalid
-            (Token::Plus, Expectation::Number) | (Token::Mul, Expectation::Number) => {
-                return None;
+        if c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
             }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            expect_operand = false;
+            continue;
+        }
 
-            // If we are expecting an operator but got a number, that means no operator between them
-            (Token::Number(_), Expectation::Operator) => {
-                return None;
+        match c {
+            '(' => {
+                tokens.push(Token::LParen);
+                expect_operand = true;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                expect_operand = false;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                expect_operand = true;
             }
+            '-' if expect_operand => {
+                tokens.push(Token::Neg);
+                // expect_operand stays true: `--5` is `-(-5)`.
+            }
+            _ if operator(c).is_some() => {
+                tokens.push(Token::Op(c));
+                expect_operand = true;
+            }
+            _ => {} // skip unrecognized characters
         }
+        i += 1;
     }
 
-    // At the end, if we were expecting an operator, that means expression ended with a number
-    // which is okay, but we must add the last product to terms.
-    // If expectation was Number, that means it ended with an operator, which is invalid.
-    match expectation {
-        Expectation::Number => {
-            // Expression ended expecting a number, means ended on an operator like "1 +"
-            return None;
+    tokens
+}
+
+/// Parse a full token stream into an expression tree using precedence
+/// climbing: `parse_bin_expr` consumes operators whose precedence is at
+/// least `min_precedence`, recursing with a higher minimum for
+/// left-associative operators (so equal precedence binds left) and the
+/// same minimum for right-associative ones (so equal precedence binds
+/// right, as `^` requires).
+fn parse(tokens: &[Token]) -> Option<Expr> {
+    let mut pos = 0;
+    let expr = parse_bin_expr(tokens, &mut pos, 0)?;
+    if pos != tokens.len() {
+        // Leftover tokens mean the input wasn't a single well-formed expression.
+        return None;
+    }
+    Some(expr)
+}
+
+fn parse_bin_expr(tokens: &[Token], pos: &mut usize, min_precedence: u8) -> Option<Expr> {
+    let mut lhs = parse_primary(tokens, pos)?;
+
+    while let Some(Token::Op(symbol)) = tokens.get(*pos) {
+        let op = operator(*symbol)?;
+        if op.precedence < min_precedence {
+            break;
+        }
+        *pos += 1;
+
+        let next_min = if op.is_left_associative {
+            op.precedence + 1
+        } else {
+            op.precedence
+        };
+        let rhs = parse_bin_expr(tokens, pos, next_min)?;
+        lhs = Expr::BinOp(*symbol, Box::new(lhs), Box::new(rhs));
+    }
+
+    Some(lhs)
+}
+
+fn parse_primary(tokens: &[Token], pos: &mut usize) -> Option<Expr> {
+    match tokens.get(*pos) {
+        Some(Token::Number(v)) => {
+            *pos += 1;
+            Some(Expr::Num(*v))
+        }
+        Some(Token::Neg) => {
+            *pos += 1;
+            // Bind at `unary_minus_precedence()` rather than recursing into
+            // `parse_primary` directly, so `-2 ^ 2` reads as `-(2 ^ 2)`
+            // instead of `(-2) ^ 2`.
+            let inner = parse_bin_expr(tokens, pos, unary_minus_precedence())?;
+            Some(Expr::Neg(Box::new(inner)))
         }
-        Expectation::Operator => {
-            if let Some(prod) = current_product {
-                terms.push(prod);
+        Some(Token::Ident(name)) => {
+            let name = name.clone();
+            *pos += 1;
+            if let Some(Token::LParen) = tokens.get(*pos) {
+                *pos += 1;
+                let args = parse_call_args(tokens, pos)?;
+                match tokens.get(*pos) {
+                    Some(Token::RParen) => {
+                        *pos += 1;
+                        Some(Expr::Func(name, args))
+                    }
+                    _ => None,
+                }
             } else {
-                // No product at the end, shouldn't happen if we got this far
-                return None;
+                Some(Expr::Var(name))
+            }
+        }
+        Some(Token::LParen) => {
+            *pos += 1;
+            let inner = parse_bin_expr(tokens, pos, 0)?;
+            match tokens.get(*pos) {
+                Some(Token::RParen) => {
+                    *pos += 1;
+                    Some(Expr::Paren(Box::new(inner)))
+                }
+                _ => None,
             }
         }
+        _ => None,
     }
+}
 
-    // Now sum all terms
-    let sum: i64 = terms.into_iter().sum();
-    Some(sum)
+/// Parse a comma-separated list of function-call arguments, stopping (without
+/// consuming) at the closing `)`. Returns an empty list for `()`.
+fn parse_call_args(tokens: &[Token], pos: &mut usize) -> Option<Vec<Expr>> {
+    let mut args = Vec::new();
+    if tokens.get(*pos) == Some(&Token::RParen) {
+        return Some(args);
+    }
+    loop {
+        args.push(parse_bin_expr(tokens, pos, 0)?);
+        match tokens.get(*pos) {
+            Some(Token::Comma) => *pos += 1,
+            _ => break,
+        }
+    }
+    Some(args)
+}
+
+/// The precedence and associativity `to_rpn` should use to order a given
+/// operator-stack token. `Neg` (unary minus) uses `unary_minus_precedence()`
+/// so the shunting-yard conversion agrees with `parse_bin_expr` on readings
+/// like `-2 ^ 2`, and, being a prefix operator applied to whatever follows
+/// it, is treated as associating to the right.
+fn rpn_precedence(token: &Token) -> Option<(u8, bool)> {
+    match token {
+        Token::Op(symbol) => operator(*symbol).map(|op| (op.precedence, op.is_left_associative)),
+        Token::Neg => Some((unary_minus_precedence(), false)),
+        _ => None,
+    }
+}
+
+/// Convert a flat (no identifiers or function calls) token stream to
+/// Reverse Polish Notation using Dijkstra's shunting-yard algorithm: numbers
+/// go straight to the output; an incoming operator pops any operators
+/// already on the stack that bind at least as tightly (more tightly for a
+/// right-associative operator, since those must yield to an equal-precedence
+/// one still waiting); `(` is pushed, and `)` pops back to the matching `(`.
+fn to_rpn(tokens: &[Token]) -> Option<Vec<Token>> {
+    let mut output = Vec::new();
+    let mut op_stack: Vec<Token> = Vec::new();
+
+    for token in tokens {
+        match token {
+            Token::Number(_) => output.push(token.clone()),
+            Token::Op(_) | Token::Neg => {
+                let (precedence, is_left_associative) = rpn_precedence(token)?;
+                while let Some(top) = op_stack.last() {
+                    let Some((top_precedence, _)) = rpn_precedence(top) else {
+                        break;
+                    };
+                    let should_pop =
+                        top_precedence > precedence || (top_precedence == precedence && is_left_associative);
+                    if !should_pop {
+                        break;
+                    }
+                    output.push(op_stack.pop().unwrap());
+                }
+                op_stack.push(token.clone());
+            }
+            Token::LParen => op_stack.push(token.clone()),
+            Token::RParen => loop {
+                match op_stack.pop() {
+                    Some(Token::LParen) => break,
+                    Some(top) => output.push(top),
+                    None => return None, // mismatched parentheses
+                }
+            },
+            Token::Ident(_) | Token::Comma => return None, // not supported by this conversion
+        }
+    }
+
+    while let Some(top) = op_stack.pop() {
+        if top == Token::LParen {
+            return None; // mismatched parentheses
+        }
+        output.push(top);
+    }
+
+    Some(output)
 }
 
-/// Evaluate a subexpression defined by [start, end], then replace that portion in the original
-/// tokens with its single evaluated result, and then evaluate the entire expression.
-fn evaluate_with_parentheses(tokens: &[Token], start: usize, end: usize) -> Option<i64> {
-    // Extract the subexpression
-    let sub_tokens = &tokens[start..=end];
-    // Evaluate the subexpression on its own
-    let sub_value = evaluate_expression(sub_tokens)?;
+/// Evaluate a postfix token stream with a simple value stack: numbers push,
+/// a binary operator pops its two operands and pushes the result, and `Neg`
+/// pops a single operand and pushes its negation. Identifiers aren't
+/// supported here, since `to_rpn` never produces them.
+fn eval_rpn(rpn: &[Token]) -> Option<Value> {
+    let mut stack: Vec<Value> = Vec::new();
 
-    // Now replace this portion in the original token list with sub_value
-    let mut new_tokens = Vec::new();
-    new_tokens.extend_from_slice(&tokens[0..start]);
-    new_tokens.push(Token::Number(sub_value));
-    new_tokens.extend_from_slice(&tokens[end + 1..]);
+    for token in rpn {
+        match token {
+            Token::Number(v) => stack.push(*v),
+            Token::Neg => {
+                let negated = match stack.pop()? {
+                    Value::Int(n) => n.checked_neg().map(Value::Int),
+                    Value::Float(f) => Some(Value::Float(-f)),
+                };
+                stack.push(negated?);
+            }
+            Token::Op(symbol) => {
+                let op = operator(*symbol)?;
+                let rhs = stack.pop()?;
+                let lhs = stack.pop()?;
+                stack.push((op.apply)(lhs, rhs)?);
+            }
+            Token::Ident(_) | Token::Comma | Token::LParen | Token::RParen => return None,
+        }
+    }
 
-    // Evaluate the resulting expression
-    let results = evaluate_expression(&new_tokens);
-    results
+    match stack.len() {
+        1 => stack.pop(),
+        _ => None,
+    }
 }
 
-/// Insert parentheses into the original string representation given the token indices.
-/// This is just a heuristic reconstruction to show how parentheses are inserted.
-fn insert_parentheses(tokens: &[Token], start: usize, end: usize) -> String {
-    // We'll map token indices back to their string positions.
-    // A simple approach is to re-construct the expression from tokens and insert parentheses
-    // around the subexpression of interest by counting tokens.
-    let pieces: Vec<String> = tokens
-        .iter()
-        .map(|t| match t {
-            Token::Number(n) => n.to_string(),
-            Token::Plus => "+".to_string(),
-            Token::Mul => "*".to_string(),
+/// Render a postfix token stream back to a space-separated string, e.g.
+/// `"1 2 3 * +"`, a compact canonical form useful for spotting duplicate
+/// parenthesizations that evaluate the same expression in the same order.
+fn render_rpn(rpn: &[Token]) -> String {
+    rpn.iter()
+        .map(|token| match token {
+            Token::Number(v) => v.to_string(),
+            Token::Op(symbol) => symbol.to_string(),
+            Token::Neg => "neg".to_string(),
+            Token::Ident(name) => name.clone(),
+            Token::LParen => "(".to_string(),
+            Token::RParen => ")".to_string(),
+            Token::Comma => ",".to_string(),
         })
-        .collect();
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    // We know pieces are spaced out as in `1 + 2 * 3 ...`
-    // We'll join them with spaces and then insert parentheses.
-    // However, this won't exactly match original spacing if it was different,
-    // but will produce a logically equivalent expression.
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut with_paren = String::new();
-    for (i, p) in pieces.iter().enumerate() {
-        if i == start {
-            with_paren.push('(');
-        }
-        if !with_paren.is_empty() && !with_paren.ends_with('(') {
-            with_paren.push(' ');
-        }
-        with_paren.push_str(p);
-        if i == end {
-            with_paren.push(')');
-        }
+    /// With two operators there are `Catalan(2) = 2` ways to fully
+    /// parenthesize, and since `+` and `*` don't commute with each other,
+    /// each parenthesization should yield a distinct value.
+    #[test]
+    fn solve_enumerates_all_parenthesizations_of_a_small_sequence() {
+        let operands = vec![Value::Int(1), Value::Int(2), Value::Int(3)];
+        let operators = vec!['+', '*'];
+        let mut memo = HashMap::new();
+        let solutions = solve(&operands, &operators, &mut memo, 0, operands.len() - 1);
+
+        assert_eq!(solutions.len(), 2);
+        assert!(solutions.contains(&(Value::Int(9), "((1 + 2) * 3)".to_string())));
+        assert!(solutions.contains(&(Value::Int(7), "(1 + (2 * 3))".to_string())));
+    }
+
+    /// With three operators there are `Catalan(3) = 5` distinct binary
+    /// trees; every split point is genuinely reachable and memoization
+    /// doesn't drop or duplicate any of them.
+    #[test]
+    fn solve_counts_match_the_catalan_number() {
+        let operands = vec![Value::Int(2), Value::Int(3), Value::Int(4), Value::Int(5)];
+        let operators = vec!['+', '*', '-'];
+        let mut memo = HashMap::new();
+        let solutions = solve(&operands, &operators, &mut memo, 0, operands.len() - 1);
+        assert_eq!(solutions.len(), 5);
     }
 
-    with_paren
+    /// Evaluate `expr` through both the AST path and the shunting-yard/RPN
+    /// path, asserting they agree, and return the shared value.
+    fn eval_both(expr: &str) -> Option<Value> {
+        let ctx = Context::new();
+        let tokens = tokenize(expr);
+        let ast_value = parse(&tokens).and_then(|e| e.eval(&ctx));
+        let rpn_value = to_rpn(&tokens).and_then(|rpn| eval_rpn(&rpn));
+        assert_eq!(ast_value, rpn_value, "AST and RPN disagreed on {:?}", expr);
+        ast_value
+    }
+
+    #[test]
+    fn power_is_right_associative_and_binds_tighter_than_multiplication() {
+        assert_eq!(eval_both("2^3^2"), Some(Value::Int(512)));
+        assert_eq!(eval_both("2*3^2"), Some(Value::Int(18)));
+    }
+
+    #[test]
+    fn unary_minus_binds_looser_than_power_but_tighter_than_other_operators() {
+        assert_eq!(eval_both("-2^2"), Some(Value::Int(-4)));
+        assert_eq!(eval_both("-2*3"), Some(Value::Int(-6)));
+        assert_eq!(eval_both("-2+3"), Some(Value::Int(1)));
+    }
+
+    #[test]
+    fn division_by_zero_fails_cleanly_instead_of_panicking() {
+        assert_eq!(eval_both("1/0"), None);
+        assert_eq!((operator('/').unwrap().apply)(Value::Int(1), Value::Int(0)), None);
+    }
+
+    #[test]
+    fn ast_and_rpn_agree_across_a_spread_of_expressions() {
+        for expr in ["1+2*3", "(1+2)*3", "10-2-3", "2^3^2", "1.5+2.5*2", "(1+2)*(3-4)/5"] {
+            eval_both(expr);
+        }
+    }
 }